@@ -0,0 +1,336 @@
+#![no_std]
+#![no_main]
+
+//! Alternative top-level built on `embassy-executor`.
+//!
+//! `main.rs` hand-wires RTIC: fixed-priority tasks bound to `RADIO`,
+//! `TIMER0` and `TIMER1`, a shared `AtomicU8 VALUE`, and manual `.lock()`s
+//! to cross between them. This binary keeps exactly the same `rubble`
+//! `BeaconScanner`/`BeaconScanCallback` scanning logic, but drives it from
+//! one `async fn` task that awaits radio/timer events instead of binding
+//! ISRs directly, and paces the LED animation from a second task against a
+//! hardware tick instead of a `TIMER1` interrupt. The smoothed RSSI moves
+//! between them over an `embassy-sync` `Signal` rather than a raw atomic,
+//! and the executor naturally `WFE`s between awaits instead of needing
+//! RTIC's priority ceiling juggling to stay idle.
+//!
+//! None of `RADIO`, `TIMER0` or the frame tick's `RTC1` are embassy-aware
+//! peripherals here (`rubble_nrf5x`'s drivers still own `RADIO`/`TIMER0`
+//! directly, and there's no registered `embassy-time` driver to back
+//! `embassy_nrf`'s own RTC-based one), so `scan_task`/`display_task` bridge
+//! their interrupts to async the same way embassy's own drivers do for
+//! custom peripherals: a bound ISR masks itself and wakes an `AtomicWaker`,
+//! and a small `Future` re-arms the interrupt before polling again.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::waitqueue::AtomicWaker;
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use static_cell::StaticCell;
+
+use microbit::display::nonblocking::Display;
+use microbit::hal::clocks::Clocks;
+use microbit::pac::{self, interrupt};
+use microbit::Board;
+use rubble::beacon::{BeaconScanner, ScanCallback};
+use rubble::link::ad_structure::AdStructure;
+use rubble::link::{DeviceAddress, Metadata, MIN_PDU_BUF};
+use rubble::time::{Duration, Timer as BleTimerExt};
+use rubble_nrf5x::radio::{BleRadio, PacketBuffer};
+use rubble_nrf5x::timer::BleTimer;
+
+#[path = "../command.rs"]
+mod command;
+#[path = "../filter.rs"]
+mod filter;
+#[path = "../frames.rs"]
+mod frames;
+#[path = "../mono.rs"]
+mod mono;
+#[path = "../track.rs"]
+mod track;
+#[path = "../watchdog.rs"]
+mod watchdog;
+
+use filter::AddressAllowList;
+use frames::FRAMES;
+use track::BeaconTable;
+
+/// Smoothed RSSI of the nearest tracked beacon, published by `scan_task` and
+/// awaited by `display_task` - the async equivalent of `main.rs`'s
+/// `VALUE: AtomicU8`.
+static RSSI: Signal<ThreadModeRawMutex, u8> = Signal::new();
+
+static RADIO_WAKER: AtomicWaker = AtomicWaker::new();
+static TIMER0_WAKER: AtomicWaker = AtomicWaker::new();
+static FRAME_WAKER: AtomicWaker = AtomicWaker::new();
+
+struct BeaconScanCallback {
+    table: BeaconTable,
+    allow_list: AddressAllowList,
+}
+
+impl BeaconScanCallback {
+    fn new(allow_list: AddressAllowList) -> Self {
+        BeaconScanCallback {
+            table: BeaconTable::default(),
+            allow_list,
+        }
+    }
+}
+
+impl ScanCallback for BeaconScanCallback {
+    fn beacon<'a, I>(&mut self, addr: DeviceAddress, data: I, metadata: Metadata)
+    where
+        I: Iterator<Item = AdStructure<'a>>,
+    {
+        if !self.allow_list.matches_service(data) {
+            return;
+        }
+        let Some(rssi) = metadata.rssi else {
+            return;
+        };
+        let rssi = (rssi.abs() as u8).saturating_sub(command::TUNABLES.offset());
+        if self.table.record(addr, rssi, mono::now()).is_some() {
+            if let Some(nearest) = self.table.nearest() {
+                RSSI.signal(nearest);
+            }
+        }
+    }
+}
+
+/// Resolves the next time `RADIO` fires, masking the interrupt on arrival so
+/// `scan_task` can re-arm it itself once it's done with the radio. Mirrors
+/// `main.rs`'s `#[task(binds = RADIO)]`, just as an `.await` point.
+struct RadioEvent;
+
+impl Future for RadioEvent {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        RADIO_WAKER.register(cx.waker());
+        if pac::NVIC::is_pending(pac::Interrupt::RADIO) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[interrupt]
+fn RADIO() {
+    pac::NVIC::mask(pac::Interrupt::RADIO);
+    RADIO_WAKER.wake();
+}
+
+/// Same bridge as [`RadioEvent`], for `TIMER0`'s window-expiry interrupt.
+struct Timer0Event;
+
+impl Future for Timer0Event {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        TIMER0_WAKER.register(cx.waker());
+        if pac::NVIC::is_pending(pac::Interrupt::TIMER0) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[interrupt]
+fn TIMER0() {
+    pac::NVIC::mask(pac::Interrupt::TIMER0);
+    TIMER0_WAKER.wake();
+}
+
+/// Services `mono`'s RTC0 overflow interrupt, same as `main.rs`'s `#[task(binds = RTC0)]`.
+/// Without this, `OVERFLOWS` never advances past 0 and `mono::now()` saws
+/// back to the same value every 512s wrap instead of producing a
+/// monotonic timestamp.
+#[interrupt]
+fn RTC0() {
+    mono::on_overflow();
+}
+
+/// Row refresh period the non-blocking `Display` needs driven continuously
+/// to multiplex the LED matrix, matching the `TIMER1` rate `main.rs` gets
+/// for free from the bound hardware interrupt. Expressed in RTC ticks
+/// (32.768 kHz, no prescaler) rather than `embassy_time::Duration`: nothing
+/// in this binary registers an `embassy-time` driver (`embassy-nrf`'s
+/// `time-driver-rtcX` would contend with `mono` for ownership of an RTC
+/// peripheral anyway), so the tick is driven off `RTC1`, a peripheral this
+/// binary owns outright, through the same ISR-to-`AtomicWaker` bridge as
+/// [`RadioEvent`]/[`Timer0Event`].
+const FRAME_PERIOD_TICKS: u32 = 4 * 32_768 / 1000;
+
+/// Resolves once per [`FRAME_PERIOD_TICKS`] RTC1 ticks.
+struct FrameTick;
+
+impl Future for FrameTick {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        FRAME_WAKER.register(cx.waker());
+        if pac::NVIC::is_pending(pac::Interrupt::RTC1) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[interrupt]
+fn RTC1() {
+    let rtc1 = unsafe { &*pac::RTC1::ptr() };
+    rtc1.events_compare[0].write(|w| unsafe { w.bits(0) });
+    let next = rtc1.cc[0].read().bits().wrapping_add(FRAME_PERIOD_TICKS);
+    rtc1.cc[0].write(|w| unsafe { w.bits(next) });
+    pac::NVIC::mask(pac::Interrupt::RTC1);
+    // The compare event was cleared above, so nothing re-pends RTC1 on its
+    // own; pend it by hand so `FrameTick` sees it when polled.
+    pac::NVIC::pend(pac::Interrupt::RTC1);
+    FRAME_WAKER.wake();
+}
+
+/// Starts RTC1 free-running at 32.768 kHz with its first compare-0 event
+/// one [`FRAME_PERIOD_TICKS`] out; `RTC1` itself re-arms the next one each
+/// time it fires.
+fn init_frame_tick(rtc1: pac::RTC1) {
+    rtc1.prescaler.write(|w| unsafe { w.bits(0) });
+    rtc1.cc[0].write(|w| unsafe { w.bits(FRAME_PERIOD_TICKS) });
+    rtc1.intenset.write(|w| w.compare0().set_bit());
+    rtc1.tasks_start.write(|w| unsafe { w.bits(1) });
+}
+
+/// Replaces `main.rs`'s `radio`/`timer0` RTIC tasks: awaits whichever of the
+/// radio or BLE timer fires next, feeds the watchdog, and re-arms the
+/// scanner exactly the way the ISR-bound handlers did.
+#[embassy_executor::task]
+async fn scan_task(
+    mut radio: BleRadio,
+    mut ble_timer: BleTimer<pac::TIMER0>,
+    mut scanner: BeaconScanner<BeaconScanCallback, AddressAllowList>,
+) {
+    loop {
+        select(RadioEvent, Timer0Event).await;
+        crate::watchdog::feed();
+
+        if pac::NVIC::is_pending(pac::Interrupt::RADIO) {
+            if let Some(next_update) = radio.recv_beacon_interrupt(ble_timer.now(), &mut scanner) {
+                ble_timer.configure_interrupt(next_update);
+            }
+            unsafe {
+                pac::NVIC::unpend(pac::Interrupt::RADIO);
+                pac::NVIC::unmask(pac::Interrupt::RADIO);
+            }
+        }
+
+        if ble_timer.is_interrupt_pending() {
+            ble_timer.clear_interrupt();
+            let cmd = scanner.timer_update(ble_timer.now());
+            radio.configure_receiver(cmd.radio);
+            ble_timer.configure_interrupt(cmd.next_update);
+            unsafe {
+                pac::NVIC::unpend(pac::Interrupt::TIMER0);
+                pac::NVIC::unmask(pac::Interrupt::TIMER0);
+            }
+        }
+    }
+}
+
+/// Replaces `main.rs`'s `#[task(binds = TIMER1)]`. `handle_display_event()`
+/// must run every [`FRAME_PERIOD_TICKS`] regardless of whether a new RSSI
+/// sample has arrived, so this selects [`FrameTick`] against `RSSI.wait()`
+/// instead of blocking on the signal: a fresh sample only ever updates
+/// which frame is latched in for the next tick, it never replaces one.
+#[embassy_executor::task]
+async fn display_task(mut display: Display<pac::TIMER1>) {
+    let mut last = u8::MAX;
+    let mut latest = 0u8;
+    loop {
+        match select(FrameTick, RSSI.wait()).await {
+            Either::First(()) => {
+                let frame = core::cmp::min(26, latest);
+                if frame != last {
+                    display.show(&FRAMES[frame as usize]);
+                    last = frame;
+                }
+                display.handle_display_event();
+                unsafe {
+                    pac::NVIC::unpend(pac::Interrupt::RTC1);
+                    pac::NVIC::unmask(pac::Interrupt::RTC1);
+                }
+            }
+            Either::Second(rssi) => latest = rssi,
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    rtt_init_print!();
+
+    let device = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+    let board = Board::new(device, core);
+
+    // RTC0 (`mono`), RTC1 (the frame tick) and the WDT all run off LFCLK,
+    // which `enable_ext_hfosc()` alone doesn't start.
+    let _clocks = Clocks::new(board.CLOCK)
+        .enable_ext_hfosc()
+        .set_lfclk_src_rc()
+        .start_lfclk();
+    mono::init(board.RTC0);
+    init_frame_tick(board.RTC1);
+    watchdog::init(&board.WDT);
+
+    static TX_BUF: StaticCell<PacketBuffer> = StaticCell::new();
+    static RX_BUF: StaticCell<PacketBuffer> = StaticCell::new();
+    let ble_tx_buf = TX_BUF.init([0; MIN_PDU_BUF]);
+    let ble_rx_buf = RX_BUF.init([0; MIN_PDU_BUF]);
+
+    let mut ble_timer = BleTimer::init(board.TIMER0);
+    let mut radio = BleRadio::new(board.RADIO, &board.FICR, ble_tx_buf, ble_rx_buf);
+
+    let allow_list = AddressAllowList::default();
+    let mut scanner = BeaconScanner::new(BeaconScanCallback::new(allow_list.clone()));
+    *scanner.filter_mut() = allow_list;
+    let scanner_cmd = scanner.configure(
+        ble_timer.now(),
+        Duration::millis(command::TUNABLES.interval_ms()),
+    );
+    radio.configure_receiver(scanner_cmd.radio);
+    ble_timer.configure_interrupt(scanner_cmd.next_update);
+
+    let display = Display::new(board.TIMER1, board.display_pins);
+
+    // `RADIO`/`TIMER0`/`RTC1` come up masked after reset; unmask them here,
+    // once the scanner is armed and both tasks are about to be spawned, so
+    // `RadioEvent`/`Timer0Event`/`FrameTick` actually get polled again after
+    // their ISRs fire instead of sitting `Pending` forever. `RTC0` has no
+    // corresponding future (`mono::on_overflow` is fire-and-forget), so it
+    // just needs unmasking, not unpending/repending bookkeeping.
+    unsafe {
+        pac::NVIC::unpend(pac::Interrupt::RADIO);
+        pac::NVIC::unpend(pac::Interrupt::TIMER0);
+        pac::NVIC::unpend(pac::Interrupt::RTC1);
+        pac::NVIC::unmask(pac::Interrupt::RADIO);
+        pac::NVIC::unmask(pac::Interrupt::TIMER0);
+        pac::NVIC::unmask(pac::Interrupt::RTC1);
+        pac::NVIC::unmask(pac::Interrupt::RTC0);
+    }
+
+    rprintln!("nRF52 scanner ready (embassy)!");
+
+    spawner.spawn(scan_task(radio, ble_timer, scanner)).unwrap();
+    spawner.spawn(display_task(display)).unwrap();
+}
@@ -0,0 +1,124 @@
+//! Runtime tuning command parser.
+//!
+//! A handful of constants used to be compiled in: the RSSI aging window
+//! (used to be `MAX_DELAY` in the scan callback, now `track`'s per-slot
+//! aging window), the `rssi.saturating_sub(...)` offset, and the scan
+//! interval passed to `BeaconScanner::configure`. Any tuning experiment
+//! meant a reflash. This module parses a tiny SCPI-style `verb noun [arg]`
+//! grammar read line-by-line from the debug channel and stores the results
+//! in [`TUNABLES`], which `track::BeaconTable` and the scanner
+//! reconfiguration path in `app::idle` read live.
+//!
+//! Supported commands, one per line:
+//!
+//! ```text
+//! SET DELAY <microseconds>
+//! SET OFFSET <0-255>
+//! SET INTERVAL <milliseconds>
+//! GET RSSI
+//! ```
+
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+/// Tunables read live by the scan callback and the scanner reconfiguration
+/// path; defaults match the values that used to be compiled in.
+pub struct Tunables {
+    delay_us: AtomicU64,
+    offset: AtomicU8,
+    interval_ms: AtomicU32,
+}
+
+pub static TUNABLES: Tunables = Tunables {
+    delay_us: AtomicU64::new(250_000),
+    offset: AtomicU8::new(42),
+    interval_ms: AtomicU32::new(500),
+};
+
+impl Tunables {
+    pub fn delay_us(&self) -> u64 {
+        self.delay_us.load(Ordering::Relaxed)
+    }
+
+    pub fn offset(&self) -> u8 {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    pub fn interval_ms(&self) -> u32 {
+        self.interval_ms.load(Ordering::Relaxed)
+    }
+}
+
+/// A parsed command line.
+pub enum Command {
+    SetDelay(u64),
+    SetOffset(u8),
+    SetInterval(u32),
+    GetRssi,
+}
+
+/// Parses one line of the `verb noun [arg]` grammar. Returns `None` for
+/// blank, malformed, or unrecognized input rather than erroring: a typo on
+/// the debug channel should never be able to upset the scanner.
+pub fn parse(line: &str) -> Option<Command> {
+    let mut words = line.split_whitespace();
+    match (words.next()?, words.next()?) {
+        ("SET", "DELAY") => Some(Command::SetDelay(words.next()?.parse().ok()?)),
+        ("SET", "OFFSET") => Some(Command::SetOffset(words.next()?.parse().ok()?)),
+        ("SET", "INTERVAL") => Some(Command::SetInterval(words.next()?.parse().ok()?)),
+        ("GET", "RSSI") => Some(Command::GetRssi),
+        _ => None,
+    }
+}
+
+/// Applies a parsed `SET` command to [`TUNABLES`]. `GetRssi` is left to the
+/// caller, which has access to the live scanner/table state this module
+/// doesn't.
+pub fn apply(command: &Command) {
+    match *command {
+        Command::SetDelay(us) => TUNABLES.delay_us.store(us, Ordering::Relaxed),
+        Command::SetOffset(offset) => TUNABLES.offset.store(offset, Ordering::Relaxed),
+        Command::SetInterval(ms) => TUNABLES.interval_ms.store(ms, Ordering::Relaxed),
+        Command::GetRssi => {}
+    }
+}
+
+/// Accumulates bytes into lines, splitting on `\r`/`\n`.
+pub struct LineReader {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl LineReader {
+    pub const fn new() -> Self {
+        LineReader {
+            buf: [0; 64],
+            len: 0,
+        }
+    }
+
+    /// Feeds one byte; returns the completed line once `byte` terminates
+    /// one. A line that doesn't fit the buffer is dropped rather than
+    /// silently truncated and misparsed.
+    pub fn feed(&mut self, byte: u8) -> Option<&str> {
+        if byte == b'\n' || byte == b'\r' {
+            if self.len == 0 {
+                return None;
+            }
+            let len = self.len;
+            self.len = 0;
+            if len > self.buf.len() {
+                return None;
+            }
+            core::str::from_utf8(&self.buf[..len]).ok()
+        } else if self.len < self.buf.len() {
+            self.buf[self.len] = byte;
+            self.len += 1;
+            None
+        } else {
+            // Mark the line as overflowed so the rest of it, up to its
+            // terminator, is discarded too.
+            self.len = self.buf.len() + 1;
+            None
+        }
+    }
+}
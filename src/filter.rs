@@ -0,0 +1,89 @@
+//! Address / service-UUID allow-list scan filter.
+//!
+//! `init` previously scanned with `AllowAll`, which hands every advertiser
+//! in range to `BeaconScanCallback::beacon`. This filter lets the scanner
+//! be pinned to a fixed set of known beacon addresses instead. Matching on
+//! an advertised 128-bit service UUID needs the AD payload, which isn't
+//! available at the point `rubble::link::filter::Filter` runs, so that
+//! check is applied from `BeaconScanCallback::beacon` via
+//! [`AddressAllowList::matches_service`] instead. Configuring both an
+//! address list and a service UUID ANDs the two: an address not in the
+//! list is rejected here, and `matches_service` applies the other half
+//! once the AD payload is in hand.
+
+use rubble::link::ad_structure::AdStructure;
+use rubble::link::filter::{AllowAll, Filter};
+use rubble::link::DeviceAddress;
+use rubble::uuid::Uuid;
+
+/// Max number of addresses the allow-list can hold.
+pub const MAX_ALLOWED: usize = 8;
+
+/// Allows advertisers whose address is in a fixed list and (if a service
+/// UUID has also been configured) whose AD payload advertises it, per
+/// [`matches_service`](Self::matches_service). Either constraint can be
+/// left unset, in which case it doesn't narrow the result; an allow-list
+/// with neither set behaves like [`AllowAll`].
+#[derive(Clone)]
+pub struct AddressAllowList {
+    addrs: [Option<DeviceAddress>; MAX_ALLOWED],
+    service: Option<Uuid>,
+}
+
+impl Default for AddressAllowList {
+    fn default() -> Self {
+        AddressAllowList {
+            addrs: [None; MAX_ALLOWED],
+            service: None,
+        }
+    }
+}
+
+impl AddressAllowList {
+    /// Adds `addr` to the allow-list, if there's a free slot.
+    pub fn allow_address(&mut self, addr: DeviceAddress) {
+        if let Some(slot) = self.addrs.iter_mut().find(|s| s.is_none()) {
+            *slot = Some(addr);
+        }
+    }
+
+    /// Restricts scanning to beacons that advertise `uuid` as a service.
+    pub fn allow_service(&mut self, uuid: Uuid) {
+        self.service = Some(uuid);
+    }
+
+    fn has_constraints(&self) -> bool {
+        self.service.is_some() || self.addrs.iter().any(|a| a.is_some())
+    }
+
+    /// Checks the advertising data against the configured service UUID, if
+    /// any. Called from `BeaconScanCallback::beacon`, which has the
+    /// `AdStructure` iterator that the `Filter` trait doesn't carry.
+    pub fn matches_service<'a>(&self, data: impl Iterator<Item = AdStructure<'a>>) -> bool {
+        let Some(want) = self.service else {
+            return true;
+        };
+        for ad in data {
+            if let AdStructure::ServiceUuids128 { uuids, .. } = ad {
+                if uuids.iter().any(|uuid| Uuid::from(*uuid) == want) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl Filter for AddressAllowList {
+    fn filter(&mut self, addr: DeviceAddress) -> bool {
+        if !self.has_constraints() {
+            return AllowAll.filter(addr);
+        }
+        // An address list and a service UUID are ANDed: the address check
+        // runs here since that's all `Filter` sees, and `matches_service`
+        // applies the other half from `beacon` once the AD payload is in
+        // hand. With no address list configured, every address passes this
+        // half so the service check alone decides.
+        self.addrs.iter().all(Option::is_none) || self.addrs.iter().flatten().any(|a| *a == addr)
+    }
+}
@@ -1,59 +1,86 @@
 #![no_std]
 #![no_main]
 
+#[cfg(all(feature = "log-rtt", feature = "log-uart"))]
+compile_error!("enable only one of the `log-rtt` / `log-uart` features, not both");
+#[cfg(not(any(feature = "log-rtt", feature = "log-uart")))]
+compile_error!("enable one of the `log-rtt` / `log-uart` features to select a logging backend");
+
 use panic_rtt_target as _;
+mod command;
+mod filter;
 mod frames;
+mod mono;
+mod serial_log;
+mod track;
+mod watchdog;
 
 #[rtic::app(device = microbit::pac, peripherals = true)]
 mod app {
-    use core::cmp::{max, min};
+    use core::cmp::min;
     use core::mem::MaybeUninit;
     use core::sync::atomic::{AtomicU8, Ordering};
 
+    use log::{info, LevelFilter};
     use microbit::display::nonblocking::Display;
     use microbit::hal::clocks::Clocks;
+    #[cfg(feature = "log-uart")]
+    use microbit::hal::gpio::Level;
+    #[cfg(feature = "log-uart")]
+    use microbit::hal::uarte::{Baudrate, Parity, Pins as UartePins, Uarte};
     use microbit::{pac, Board};
-    use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
-    use rtt_target::{rprint, rprintln, rtt_init, set_print_channel, ChannelMode};
+    use rtt_target::{rtt_init, set_print_channel, ChannelMode, DownChannel};
     use rubble::beacon::{BeaconScanner, ScanCallback};
     use rubble::link::ad_structure::AdStructure;
-    use rubble::link::filter::AllowAll;
     use rubble::link::{DeviceAddress, Metadata, MIN_PDU_BUF};
     use rubble::time::{Duration, Timer};
     use rubble_nrf5x::radio::{BleRadio, PacketBuffer};
     use rubble_nrf5x::timer::BleTimer;
 
+    use crate::command::{self, Command, LineReader};
+    use crate::filter::AddressAllowList;
     use crate::frames::FRAMES;
-
+    #[cfg(feature = "log-rtt")]
+    use crate::serial_log::RttLogger;
+    #[cfg(feature = "log-uart")]
+    use crate::serial_log::{reader, Reader, SerialLogger};
+    use crate::track::BeaconTable;
+
+    /// Chunk size used when draining the serial log ring buffer into the
+    /// UARTE DMA transfer; the peripheral's EasyDMA buffer is the limiting
+    /// factor, not the ring buffer itself.
+    #[cfg(feature = "log-uart")]
+    const UART_CHUNK: usize = 64;
+
+    #[cfg(feature = "log-uart")]
+    static LOGGER: SerialLogger = SerialLogger;
+    #[cfg(feature = "log-rtt")]
+    static LOGGER: RttLogger = RttLogger;
+
+    /// Smoothed RSSI of the nearest tracked beacon, read by `timer1` to
+    /// drive the LED display.
     static VALUE: AtomicU8 = AtomicU8::new(0);
 
-    #[derive(Copy, Clone)]
-    struct RSSIEntry {
-        timestamp: u32,
-        rssi: u8,
-    }
-
-    impl Default for RSSIEntry {
-        fn default() -> Self {
-            RSSIEntry {
-                timestamp: 0,
-                rssi: u8::MAX,
-            }
-        }
-    }
+    /// Beacon addresses the scanner is pinned to; see the allow-list setup
+    /// in `init`. Empty means "scan everything".
+    const TARGET_ADDRESSES: [DeviceAddress; 0] = [];
 
     pub struct BeaconScanCallback {
-        log: ConstGenericRingBuffer<RSSIEntry, 32>,
-        rssi_window: ConstGenericRingBuffer<u8, 4>,
+        table: BeaconTable,
+        allow_list: AddressAllowList,
     }
 
-    impl Default for BeaconScanCallback {
-        fn default() -> Self {
+    impl BeaconScanCallback {
+        pub fn new(allow_list: AddressAllowList) -> Self {
             BeaconScanCallback {
-                log: ConstGenericRingBuffer::new(),
-                rssi_window: ConstGenericRingBuffer::new(),
+                table: BeaconTable::default(),
+                allow_list,
             }
         }
+
+        pub fn table(&self) -> &BeaconTable {
+            &self.table
+        }
     }
 
     impl ScanCallback for BeaconScanCallback {
@@ -61,70 +88,19 @@ mod app {
         where
             I: Iterator<Item = AdStructure<'a>>,
         {
-            //rprint!(
-            //    "[{:?}] CH:{:?} Type:{:?} ",
-            //    metadata.timestamp.unwrap().ticks(),
-            //    metadata.channel,
-            //    metadata.pdu_type.unwrap(),
-            //);
+            if !self.allow_list.matches_service(data) {
+                return;
+            }
             if let Some(rssi) = metadata.rssi {
                 let mut rssi = rssi.abs() as u8;
-                rssi = rssi.saturating_sub(42);
-                let entry = RSSIEntry {
-                    timestamp: metadata.timestamp.unwrap().ticks(),
-                    rssi: rssi,
-                };
-                self.log.enqueue(entry);
-                let getstamp = match self.log.get_signed(-(self.log.len() as isize)) {
-                    Some(get) => get.timestamp,
-                    None => 0,
-                };
-                let diff = entry.timestamp.wrapping_sub(getstamp);
-
-                const MAX_DELAY: u32 = 250_000;
-                if self.log.is_full() || diff > MAX_DELAY {
-                    let mut min_rssi = u8::MAX;
-                    let mut valid_items: usize = 0;
-                    for item in self.log.iter().rev() {
-                        let diff = entry.timestamp.wrapping_sub(item.timestamp);
-
-                        if diff < MAX_DELAY {
-                            min_rssi = min(min_rssi, item.rssi);
-                            if diff < MAX_DELAY {
-                                valid_items += 1;
-                            }
-                        } else {
-                            break;
-                        }
-                    }
-
-                    while valid_items > 0 {
-                        self.log.skip();
-                        valid_items -= 1;
-                    }
-
-                    let mut avg_min_rssi: u32 = 0;
-                    self.rssi_window.enqueue(min_rssi);
-                    for i in self.rssi_window.iter() {
-                        avg_min_rssi += *i as u32;
-                    }
-                    avg_min_rssi /= self.rssi_window.len() as u32;
-                    if self.rssi_window.is_full() {
-                        self.rssi_window.skip();
-                    }
-
-                    rprintln!("avg_min_rssi: {}", avg_min_rssi);
-                    VALUE.store(avg_min_rssi as u8, Ordering::SeqCst);
+                rssi = rssi.saturating_sub(command::TUNABLES.offset());
+                if let Some(smoothed) = self.table.record(addr, rssi, crate::mono::now()) {
+                    info!("{:?} avg_min_rssi: {}", addr, smoothed);
+                }
+                if let Some(nearest) = self.table.nearest() {
+                    VALUE.store(nearest, Ordering::SeqCst);
                 }
             }
-            //rprint!("BDADDR:{:?} DATA:", addr);
-            //let mut first = true;
-            //for packet in data {
-            //    rprint!("{}{:02x?}", if first { " " } else { " / " }, packet);
-            //    first = false;
-            //}
-            //rprintln!("");
-            //rprintln!("");
         }
     }
 
@@ -132,13 +108,25 @@ mod app {
     struct Shared {
         radio: BleRadio,
         ble_timer: BleTimer<pac::TIMER0>,
-        scanner: BeaconScanner<BeaconScanCallback, AllowAll>,
+        scanner: BeaconScanner<BeaconScanCallback, AddressAllowList>,
         display: Display<pac::TIMER1>,
     }
 
     #[local]
     struct Local {
         last_rssi: u8,
+        /// `timer1` tick counter; every `CYCLE_PERIOD` ticks the display
+        /// advances to the next tracked beacon instead of showing nearest.
+        cycle_tick: u16,
+        cycle_idx: usize,
+        /// RTT down-channel commands are read from, regardless of which
+        /// sink `LOGGER` prints to; see `idle`.
+        down_channel: DownChannel,
+        line_reader: LineReader,
+        #[cfg(feature = "log-uart")]
+        uart_reader: Reader,
+        #[cfg(feature = "log-uart")]
+        uarte: Uarte<pac::UARTE0>,
     }
 
     #[init(local=[
@@ -153,12 +141,32 @@ mod app {
                     mode: ChannelMode::NoBlockTrim,
                     name: "Microscan Logs"
                 }
+            },
+            down: {
+                0: {
+                    size: 64,
+                    mode: ChannelMode::NoBlockSkip,
+                    name: "Microscan Commands"
+                }
             }
         };
         set_print_channel(rtt.up.0);
+        let down_channel = rtt.down.0;
+
+        log::set_logger(&LOGGER).ok();
+        log::set_max_level(LevelFilter::Info);
+
         let board = Board::new(ctx.device, ctx.core);
 
-        let _clocks = Clocks::new(board.CLOCK).enable_ext_hfosc();
+        // RTC0 (`mono`) and the WDT both run off LFCLK, which
+        // `enable_ext_hfosc()` alone doesn't start.
+        let _clocks = Clocks::new(board.CLOCK)
+            .enable_ext_hfosc()
+            .set_lfclk_src_rc()
+            .start_lfclk();
+
+        crate::mono::init(board.RTC0);
+        crate::watchdog::init(&board.WDT);
 
         let mut ble_timer = BleTimer::init(board.TIMER0);
 
@@ -166,15 +174,39 @@ mod app {
         let ble_tx_buf: &'static mut _ = ctx.local.tx_buf.write([0; MIN_PDU_BUF]);
         let mut radio = BleRadio::new(board.RADIO, &board.FICR, ble_tx_buf, ble_rx_buf);
 
-        let mut scanner = BeaconScanner::new(BeaconScanCallback::default());
-        let scanner_cmd = scanner.configure(ble_timer.now(), Duration::millis(500));
+        // Pin the scanner to specific beacons by adding addresses here (or
+        // call `allow_list.allow_service(uuid)` for a 128-bit service
+        // UUID); left empty, the allow-list matches everything, same as the
+        // old `AllowAll` filter.
+        let mut allow_list = AddressAllowList::default();
+        for addr in TARGET_ADDRESSES {
+            allow_list.allow_address(addr);
+        }
+
+        let mut scanner = BeaconScanner::new(BeaconScanCallback::new(allow_list.clone()));
+        *scanner.filter_mut() = allow_list;
+        let scanner_cmd =
+            scanner.configure(ble_timer.now(), Duration::millis(command::TUNABLES.interval_ms()));
 
         radio.configure_receiver(scanner_cmd.radio);
         ble_timer.configure_interrupt(scanner_cmd.next_update);
 
         let display = Display::new(board.TIMER1, board.display_pins);
 
-        rprintln!("nRF52 scanner ready!");
+        #[cfg(feature = "log-uart")]
+        let uarte = Uarte::new(
+            board.UARTE0,
+            UartePins {
+                txd: board.pins.p0_06.into_push_pull_output(Level::High).degrade(),
+                rxd: board.pins.p0_08.into_floating_input().degrade(),
+                cts: None,
+                rts: None,
+            },
+            Parity::EXCLUDED,
+            Baudrate::BAUD115200,
+        );
+
+        info!("nRF52 scanner ready!");
 
         (
             Shared {
@@ -183,14 +215,118 @@ mod app {
                 ble_timer,
                 display,
             },
-            Local { last_rssi: 0 },
+            Local {
+                last_rssi: 0,
+                cycle_tick: 0,
+                cycle_idx: 0,
+                down_channel,
+                line_reader: LineReader::new(),
+                #[cfg(feature = "log-uart")]
+                uart_reader: reader(),
+                #[cfg(feature = "log-uart")]
+                uarte,
+            },
             init::Monotonics(),
         )
     }
 
-    #[task(binds = TIMER1, priority = 2, shared = [display], local = [last_rssi])]
+    /// Applies a parsed command, handling the parts that need access to the
+    /// live scanner/timer/radio (`SET INTERVAL`'s live reconfigure, `GET
+    /// RSSI`'s reply) in addition to the plain atomic stores `command::apply`
+    /// already covers.
+    fn dispatch_command(
+        command: Command,
+        scanner: &mut BeaconScanner<BeaconScanCallback, AddressAllowList>,
+        timer: &mut BleTimer<pac::TIMER0>,
+        radio: &mut BleRadio,
+    ) {
+        command::apply(&command);
+        match command {
+            Command::SetInterval(ms) => {
+                let cmd = scanner.configure(timer.now(), Duration::millis(ms));
+                radio.configure_receiver(cmd.radio);
+                timer.configure_interrupt(cmd.next_update);
+            }
+            Command::GetRssi => {
+                info!(
+                    "nearest={} tracked={}",
+                    VALUE.load(Ordering::SeqCst),
+                    scanner.callback().table().tracked().count()
+                );
+            }
+            Command::SetDelay(_) | Command::SetOffset(_) => {}
+        }
+    }
+
+    #[cfg(feature = "log-uart")]
+    #[idle(shared = [scanner, ble_timer, radio], local = [uart_reader, uarte, down_channel, line_reader])]
+    fn idle(mut ctx: idle::Context) -> ! {
+        let reader = ctx.local.uart_reader;
+        let uarte = ctx.local.uarte;
+        let mut chunk = [0u8; UART_CHUNK];
+        let mut byte = [0u8; 1];
+        loop {
+            let n = reader.drain_into(&mut chunk);
+            if n > 0 {
+                let _ = uarte.write(&chunk[..n]);
+            }
+
+            if ctx.local.down_channel.read(&mut byte) > 0 {
+                if let Some(command) = ctx
+                    .local
+                    .line_reader
+                    .feed(byte[0])
+                    .and_then(command::parse)
+                {
+                    (ctx.shared.scanner, ctx.shared.ble_timer, ctx.shared.radio).lock(
+                        |scanner, timer, radio| dispatch_command(command, scanner, timer, radio),
+                    );
+                }
+            } else if n == 0 {
+                cortex_m::asm::wfe();
+            }
+        }
+    }
+
+    #[cfg(not(feature = "log-uart"))]
+    #[idle(shared = [scanner, ble_timer, radio], local = [down_channel, line_reader])]
+    fn idle(mut ctx: idle::Context) -> ! {
+        let mut byte = [0u8; 1];
+        loop {
+            if ctx.local.down_channel.read(&mut byte) > 0 {
+                if let Some(command) = ctx
+                    .local
+                    .line_reader
+                    .feed(byte[0])
+                    .and_then(command::parse)
+                {
+                    (ctx.shared.scanner, ctx.shared.ble_timer, ctx.shared.radio).lock(
+                        |scanner, timer, radio| dispatch_command(command, scanner, timer, radio),
+                    );
+                }
+            } else {
+                cortex_m::asm::wfe();
+            }
+        }
+    }
+
+    /// `timer1` ticks once per display refresh (fast, for LED multiplexing);
+    /// cycle through tracked beacons far slower than that.
+    const CYCLE_PERIOD: u16 = 300;
+
+    #[task(binds = TIMER1, priority = 2, shared = [display, scanner], local = [last_rssi, cycle_tick, cycle_idx])]
     fn timer1(mut ctx: timer1::Context) {
-        let rssi = VALUE.load(Ordering::SeqCst);
+        *ctx.local.cycle_tick = ctx.local.cycle_tick.wrapping_add(1);
+        let rssi = if *ctx.local.cycle_tick % CYCLE_PERIOD == 0 {
+            let idx = *ctx.local.cycle_idx;
+            *ctx.local.cycle_idx = (idx + 1) % crate::track::MAX_TRACKED;
+            ctx.shared
+                .scanner
+                .lock(|scanner| scanner.callback().table().tracked().nth(idx).map(|(_, v)| v))
+                .unwrap_or_else(|| VALUE.load(Ordering::SeqCst))
+        } else {
+            VALUE.load(Ordering::SeqCst)
+        };
         let frame = min(26, rssi);
         let last = *ctx.local.last_rssi;
         *ctx.local.last_rssi = frame;
@@ -204,6 +340,8 @@ mod app {
 
     #[task(binds = RADIO, shared = [radio, scanner, ble_timer])]
     fn radio(ctx: radio::Context) {
+        crate::watchdog::feed();
+
         let timer = ctx.shared.ble_timer;
         let scanner = ctx.shared.scanner;
         let radio = ctx.shared.radio;
@@ -218,6 +356,8 @@ mod app {
 
     #[task(binds = TIMER0, shared = [radio, ble_timer, scanner])]
     fn timer0(ctx: timer0::Context) {
+        crate::watchdog::feed();
+
         let timer = ctx.shared.ble_timer;
         let scanner = ctx.shared.scanner;
         let radio = ctx.shared.radio;
@@ -233,4 +373,9 @@ mod app {
             timer.configure_interrupt(cmd.next_update);
         });
     }
+
+    #[task(binds = RTC0)]
+    fn rtc0(_ctx: rtc0::Context) {
+        crate::mono::on_overflow();
+    }
 }
@@ -0,0 +1,82 @@
+//! RTC-backed monotonic microsecond clock.
+//!
+//! The BLE timer (`BleTimer<pac::TIMER0>`) ticks a 24-bit hardware counter
+//! that wraps roughly every 512 seconds, which is what `RSSIEntry` used to
+//! timestamp against with `wrapping_sub`. That only works as long as no
+//! aging window spans a wrap, which isn't guaranteed for a board left
+//! running in the field. This module extends the free-running 32.768 kHz
+//! RTC's 24-bit counter into a 64-bit microsecond counter by accumulating
+//! an overflow count in software, so `now()` never wraps in any realistic
+//! runtime.
+//!
+//! `init` and `on_overflow` are called from `app::init` and the `RTC0`
+//! interrupt handler respectively; `now()` is free to call from anywhere
+//! (in particular from `BeaconScanCallback::beacon`, which has no RTIC
+//! resource of its own to carry a clock through), so `now()` and
+//! `on_overflow` reach the peripheral through `RTC0::ptr()` rather than an
+//! owned handle.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use microbit::pac::RTC0;
+
+/// RTC0 runs at the full 32.768 kHz LFCLK (no prescaler), so the 24-bit
+/// counter overflows every `2^24 / 32768 = 512` seconds.
+const COUNTER_BITS: u32 = 24;
+const OVERFLOW_PERIOD_TICKS: u64 = 1 << COUNTER_BITS;
+
+/// Number of RTC overflow interrupts observed since boot.
+static OVERFLOWS: AtomicU32 = AtomicU32::new(0);
+
+/// Starts RTC0 free-running at 32.768 kHz with overflow interrupts enabled.
+/// Call once from `init`, passing the board's owned `RTC0` peripheral (it is
+/// only used here to prove exclusive access at startup time; `now()` and
+/// `on_overflow()` reach the same registers through `RTC0::ptr()` since
+/// `BeaconScanCallback::beacon` has no RTIC resource to carry a handle
+/// through).
+pub fn init(rtc0: RTC0) {
+    rtc0.prescaler.write(|w| unsafe { w.bits(0) });
+    rtc0.intenset.write(|w| w.ovrflw().set_bit());
+    rtc0.tasks_start.write(|w| unsafe { w.bits(1) });
+}
+
+/// Must be called from the RTC0 interrupt handler; bumps the software
+/// overflow count and clears the event.
+pub fn on_overflow() {
+    let rtc0 = unsafe { &*RTC0::ptr() };
+    if rtc0.events_ovrflw.read().bits() != 0 {
+        rtc0.events_ovrflw.write(|w| unsafe { w.bits(0) });
+        OVERFLOWS.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Returns the current time as a monotonic microsecond counter.
+///
+/// Reads `OVERFLOWS` before and after `counter`/`events_ovrflw` to detect
+/// (and retry past) an overflow interrupt landing mid-read. That alone
+/// isn't enough: `now()` can run from `radio`/`beacon` context at the same
+/// or higher priority than `RTC0`'s handler, so `events_ovrflw` can already
+/// be set (counter wrapped to ~0) without `on_overflow` having run yet to
+/// bump `OVERFLOWS`. Folding that pending flag into the overflow count
+/// here, before combining it with `counter`, avoids the apparent backward
+/// jump that would otherwise collapse every in-flight aging window to a
+/// zero diff. `counter` is read again once `events_ovrflw` is seen set, so
+/// that a wrap landing between the two reads never pairs a pre-wrap count
+/// with the post-wrap flag.
+pub fn now() -> u64 {
+    let rtc0 = unsafe { &*RTC0::ptr() };
+    loop {
+        let before = OVERFLOWS.load(Ordering::Acquire);
+        let mut ticks = rtc0.counter.read().bits() as u64;
+        let pending = rtc0.events_ovrflw.read().bits() != 0;
+        if pending {
+            ticks = rtc0.counter.read().bits() as u64;
+        }
+        let after = OVERFLOWS.load(Ordering::Acquire);
+        if before == after {
+            let overflows = if pending { after as u64 + 1 } else { after as u64 };
+            let total_ticks = overflows * OVERFLOW_PERIOD_TICKS + ticks;
+            // RTC0 runs at 32.768 kHz, i.e. 1 tick = 1_000_000 / 32768 us.
+            break total_ticks * 1_000_000 / 32_768;
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! UART serial logging backend.
+//!
+//! RTT requires a probe-rs/GDB session to be attached to the board, which
+//! isn't available once the board is deployed in the field. This module
+//! provides a second sink that streams log output over one of the
+//! micro:bit's UARTE peripherals instead, selectable via the `log-uart`
+//! cargo feature (the default, `log-rtt`, keeps logging on the RTT
+//! up-channel as before). The UART machinery below is only compiled in
+//! under `log-uart`; otherwise it's all dead code the default build would
+//! otherwise warn on.
+//!
+//! Both loggers are driven through the `log` crate facade, so call sites use
+//! `info!`/`debug!` rather than talking to either backend directly. The
+//! UART backend is a lock-free-on-the-read-side ring buffer: pushing to it
+//! never blocks and silently drops bytes once the buffer is full, so it can
+//! never stall BLE receive interrupt timing. The buffered bytes are drained
+//! from a low-priority RTIC task that feeds the UARTE via DMA in chunks.
+
+#[cfg(feature = "log-uart")]
+use core::fmt::{self, Write as _};
+#[cfg(feature = "log-uart")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Capacity of the ring buffer, in bytes. Must be a power of two.
+#[cfg(feature = "log-uart")]
+const CAPACITY: usize = 1024;
+
+/// Single-consumer byte ring buffer, pushed from more than one producer.
+///
+/// `info!`/`debug!` can run from both the high-priority `radio`/`beacon`
+/// interrupt context and the low-priority `idle` task (e.g. `GetRssi`
+/// replies), and `idle` can be preempted mid-`push` by the interrupt that
+/// also pushes - so `push` takes a short interrupt-free critical section to
+/// serialize the two producers around `head`. The consumer (the
+/// low-priority drain task, the only thing that ever calls `drain_into`)
+/// still only ever advances `tail`, so that side stays lock-free.
+#[cfg(feature = "log-uart")]
+struct Ring {
+    buf: [core::cell::UnsafeCell<u8>; CAPACITY],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// SAFETY: see the struct doc comment - `push` serializes producers itself,
+// and `drain_into` is only ever called by the single consumer.
+#[cfg(feature = "log-uart")]
+unsafe impl Sync for Ring {}
+
+#[cfg(feature = "log-uart")]
+impl Ring {
+    const fn new() -> Self {
+        const ZERO: core::cell::UnsafeCell<u8> = core::cell::UnsafeCell::new(0);
+        Ring {
+            buf: [ZERO; CAPACITY],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, byte: u8) {
+        cortex_m::interrupt::free(|_| {
+            let head = self.head.load(Ordering::Relaxed);
+            let tail = self.tail.load(Ordering::Acquire);
+            if head.wrapping_sub(tail) >= CAPACITY {
+                // Buffer full: drop the byte rather than block the caller.
+                return;
+            }
+            // SAFETY: this slot isn't visible to the consumer until `head`
+            // is published below, and the critical section rules out the
+            // other producer touching it concurrently.
+            unsafe {
+                *self.buf[head % CAPACITY].get() = byte;
+            }
+            self.head.store(head.wrapping_add(1), Ordering::Release);
+        });
+    }
+
+    fn drain_into(&self, chunk: &mut [u8]) -> usize {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        let mut n = 0;
+        while n < chunk.len() && tail != head {
+            // SAFETY: a producer will not touch this slot again until the
+            // consumer publishes the new `tail` below.
+            chunk[n] = unsafe { *self.buf[tail % CAPACITY].get() };
+            tail = tail.wrapping_add(1);
+            n += 1;
+        }
+        self.tail.store(tail, Ordering::Release);
+        n
+    }
+}
+
+#[cfg(feature = "log-uart")]
+static RING: Ring = Ring::new();
+
+/// Consumer half of the serial log ring buffer.
+///
+/// Obtained once via [`reader`] and drained from a low-priority RTIC task
+/// that forwards the bytes to the UARTE DMA transmitter.
+#[cfg(feature = "log-uart")]
+pub struct Reader {
+    _private: (),
+}
+
+#[cfg(feature = "log-uart")]
+impl Reader {
+    /// Copies as many buffered bytes as fit into `chunk`, returning the
+    /// number of bytes written.
+    pub fn drain_into(&mut self, chunk: &mut [u8]) -> usize {
+        RING.drain_into(chunk)
+    }
+}
+
+/// Returns the (sole) consumer half of the serial log ring buffer.
+///
+/// Call once from `init` and hand the result to the low-priority drain task.
+#[cfg(feature = "log-uart")]
+pub fn reader() -> Reader {
+    Reader { _private: () }
+}
+
+#[cfg(feature = "log-uart")]
+struct RingWriter;
+
+#[cfg(feature = "log-uart")]
+impl fmt::Write for RingWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.as_bytes() {
+            RING.push(*byte);
+        }
+        Ok(())
+    }
+}
+
+/// [`log::Log`] implementation that pushes formatted records into the
+/// serial ring buffer. Never blocks, so it is safe to call from the
+/// `radio`/`beacon` interrupt context via `info!`/`debug!`.
+#[cfg(feature = "log-uart")]
+pub struct SerialLogger;
+
+#[cfg(feature = "log-uart")]
+impl log::Log for SerialLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let _ = writeln!(RingWriter, "[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+/// [`log::Log`] implementation that forwards formatted records to the RTT
+/// up-channel via `rprintln!`. `rtt-target`'s channel is itself configured
+/// with `NoBlockTrim`, so this is non-blocking the same way `SerialLogger`
+/// is.
+#[cfg(feature = "log-rtt")]
+pub struct RttLogger;
+
+#[cfg(feature = "log-rtt")]
+impl log::Log for RttLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        rtt_target::rprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
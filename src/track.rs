@@ -0,0 +1,161 @@
+//! Per-beacon RSSI tracking table.
+//!
+//! `BeaconScanCallback` used to funnel every advertiser through one global
+//! aging log and one global smoothed RSSI (`VALUE`), so two beacons in
+//! range would blend into a single meaningless number. This module keys
+//! tracking by `DeviceAddress` instead: each tracked beacon gets its own
+//! aging window (the same logic the old single-beacon code used) and its
+//! own smoothed value, in a small fixed-capacity table with LRU eviction
+//! once it's full.
+
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
+use rubble::link::DeviceAddress;
+
+/// Max number of beacons tracked concurrently.
+pub const MAX_TRACKED: usize = 6;
+
+#[derive(Copy, Clone)]
+struct RSSIEntry {
+    timestamp: u64,
+    rssi: u8,
+}
+
+impl Default for RSSIEntry {
+    fn default() -> Self {
+        RSSIEntry {
+            timestamp: 0,
+            rssi: u8::MAX,
+        }
+    }
+}
+
+struct Slot {
+    addr: DeviceAddress,
+    log: ConstGenericRingBuffer<RSSIEntry, 32>,
+    rssi_window: ConstGenericRingBuffer<u8, 4>,
+    smoothed: u8,
+    last_seen: u64,
+}
+
+impl Slot {
+    fn new(addr: DeviceAddress, now: u64) -> Self {
+        Slot {
+            addr,
+            log: ConstGenericRingBuffer::new(),
+            rssi_window: ConstGenericRingBuffer::new(),
+            smoothed: u8::MAX,
+            last_seen: now,
+        }
+    }
+
+    /// Folds `rssi` into this slot's aging log, returning `Some(smoothed)`
+    /// whenever the window closed and a smoothed value was recomputed
+    /// (mirrors the old single-beacon `BeaconScanCallback::beacon` logic).
+    /// The aging window itself comes from [`crate::command::TUNABLES`] so
+    /// it can be tuned live over the debug channel instead of reflashed.
+    fn record(&mut self, rssi: u8, timestamp: u64) -> Option<u8> {
+        let max_delay = crate::command::TUNABLES.delay_us();
+
+        self.last_seen = timestamp;
+        let entry = RSSIEntry { timestamp, rssi };
+        self.log.enqueue(entry);
+        let getstamp = match self.log.get_signed(-(self.log.len() as isize)) {
+            Some(get) => get.timestamp,
+            None => 0,
+        };
+        let diff = entry.timestamp.saturating_sub(getstamp);
+
+        if !(self.log.is_full() || diff > max_delay) {
+            return None;
+        }
+
+        let mut min_rssi = u8::MAX;
+        let mut valid_items: usize = 0;
+        for item in self.log.iter().rev() {
+            let diff = entry.timestamp.saturating_sub(item.timestamp);
+            if diff < max_delay {
+                min_rssi = core::cmp::min(min_rssi, item.rssi);
+                valid_items += 1;
+            } else {
+                break;
+            }
+        }
+
+        while valid_items > 0 {
+            self.log.skip();
+            valid_items -= 1;
+        }
+
+        let mut avg_min_rssi: u32 = 0;
+        self.rssi_window.enqueue(min_rssi);
+        for i in self.rssi_window.iter() {
+            avg_min_rssi += *i as u32;
+        }
+        avg_min_rssi /= self.rssi_window.len() as u32;
+        if self.rssi_window.is_full() {
+            self.rssi_window.skip();
+        }
+
+        self.smoothed = avg_min_rssi as u8;
+        Some(self.smoothed)
+    }
+}
+
+/// Fixed-capacity, address-keyed RSSI tracking table with LRU eviction.
+pub struct BeaconTable {
+    slots: [Option<Slot>; MAX_TRACKED],
+}
+
+impl Default for BeaconTable {
+    fn default() -> Self {
+        const NONE: Option<Slot> = None;
+        BeaconTable {
+            slots: [NONE; MAX_TRACKED],
+        }
+    }
+}
+
+impl BeaconTable {
+    /// Records a new RSSI sample for `addr`, creating or evicting a slot as
+    /// needed. Returns `Some(smoothed)` when that beacon's aging window
+    /// closed and produced a new smoothed value.
+    pub fn record(&mut self, addr: DeviceAddress, rssi: u8, timestamp: u64) -> Option<u8> {
+        let idx = self.slot_for(addr, timestamp);
+        self.slots[idx].as_mut().unwrap().record(rssi, timestamp)
+    }
+
+    fn slot_for(&mut self, addr: DeviceAddress, now: u64) -> usize {
+        if let Some(idx) = self
+            .slots
+            .iter()
+            .position(|s| matches!(s, Some(s) if s.addr == addr))
+        {
+            return idx;
+        }
+        if let Some(idx) = self.slots.iter().position(|s| s.is_none()) {
+            self.slots[idx] = Some(Slot::new(addr, now));
+            return idx;
+        }
+        // Table full: evict whichever slot has gone longest without a beacon.
+        let evict = self
+            .slots
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, s)| s.as_ref().unwrap().last_seen)
+            .map(|(i, _)| i)
+            .unwrap();
+        self.slots[evict] = Some(Slot::new(addr, now));
+        evict
+    }
+
+    /// Smoothed RSSI of the nearest tracked beacon (lowest smoothed value).
+    pub fn nearest(&self) -> Option<u8> {
+        self.slots.iter().flatten().map(|s| s.smoothed).min()
+    }
+
+    /// Iterates `(address, smoothed)` pairs for every currently tracked
+    /// beacon, for cycling the display through all of them.
+    pub fn tracked(&self) -> impl Iterator<Item = (DeviceAddress, u8)> + '_ {
+        self.slots.iter().flatten().map(|s| (s.addr, s.smoothed))
+    }
+}
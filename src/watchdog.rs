@@ -0,0 +1,36 @@
+//! Hardware watchdog.
+//!
+//! If the radio state machine ever wedges (no beacons, no `radio`/`timer0`
+//! interrupts), nothing else in this firmware would notice and the LED
+//! display would just freeze on its last frame. The WDT is configured once
+//! in `init` and must be fed periodically from the `radio` and `timer0`
+//! tasks; if neither runs for `TIMEOUT_MS`, the WDT resets the chip.
+
+use microbit::pac::WDT;
+
+/// Reset the board if neither `radio` nor `timer0` has fed the watchdog
+/// within this many milliseconds.
+const TIMEOUT_MS: u64 = 5_000;
+
+/// The WDT's reload counter runs at the fixed 32.768 kHz LFCLK.
+const RELOAD_VALUE: u32 = (TIMEOUT_MS * 32_768 / 1000) as u32;
+
+/// Magic value the WDT requires in a reload register to accept a feed.
+const RELOAD_MAGIC: u32 = 0x6E52_4635;
+
+/// Configures and starts the watchdog using reload register 0. Call once
+/// from `init`, before the radio is armed, so a hang during startup itself
+/// is caught too.
+pub fn init(wdt: &WDT) {
+    wdt.config.write(|w| w.sleep().run().halt().pause());
+    wdt.crv.write(|w| unsafe { w.bits(RELOAD_VALUE) });
+    wdt.rren.write(|w| w.rr0().included());
+    wdt.tasks_start.write(|w| unsafe { w.bits(1) });
+}
+
+/// Feeds reload register 0, postponing the next reset by `TIMEOUT_MS`.
+/// Safe to call from any context, including interrupt handlers.
+pub fn feed() {
+    let wdt = unsafe { &*WDT::ptr() };
+    wdt.rr[0].write(|w| unsafe { w.bits(RELOAD_MAGIC) });
+}